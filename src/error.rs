@@ -0,0 +1,96 @@
+//! Error types shared by the level and replay parsers.
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+
+/// Errors that can occur while loading or saving Elasto Mania files.
+#[derive(Debug)]
+pub enum Error {
+    /// Wraps an underlying I/O error.
+    Io(io::Error),
+    /// The file did not start with a recognised magic number.
+    InvalidMagic,
+    /// The end-of-data marker did not match the expected value.
+    EodMismatch,
+    /// The end-of-file marker did not match the expected value.
+    EofMismatch,
+    /// Encountered an end-of-replay marker that did not match.
+    EorMismatch,
+    /// An object type code outside the valid 1..=4 range.
+    InvalidObjectType(i32),
+    /// A picture clip value outside the valid 0..=2 range.
+    InvalidClipValue(i32),
+    /// Wraps an underlying `binrw` (de)serialization error.
+    BinRw(binrw::Error),
+    /// Wraps an underlying JSON (de)serialization error.
+    #[cfg(feature = "serde")]
+    Json(serde_json::Error)
+}
+
+impl fmt::Display for Error {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+            Error::InvalidMagic => write!(f, "not a valid Elasto Mania file"),
+            Error::EodMismatch => write!(f, "end-of-data marker mismatch"),
+            Error::EofMismatch => write!(f, "end-of-file marker mismatch"),
+            Error::EorMismatch => write!(f, "end-of-replay marker mismatch"),
+            Error::InvalidObjectType(t) => write!(f, "invalid object type: {}", t),
+            Error::InvalidClipValue(c) => write!(f, "invalid clip value: {}", c),
+            Error::BinRw(ref e) => write!(f, "binary (de)serialization error: {}", e),
+            #[cfg(feature = "serde")]
+            Error::Json(ref e) => write!(f, "JSON (de)serialization error: {}", e)
+        }
+    }
+}
+
+impl StdError for Error {
+    fn description (&self) -> &str {
+        match *self {
+            Error::Io(_) => "I/O error",
+            Error::InvalidMagic => "not a valid Elasto Mania file",
+            Error::EodMismatch => "end-of-data marker mismatch",
+            Error::EofMismatch => "end-of-file marker mismatch",
+            Error::EorMismatch => "end-of-replay marker mismatch",
+            Error::InvalidObjectType(_) => "invalid object type",
+            Error::InvalidClipValue(_) => "invalid clip value",
+            Error::BinRw(_) => "binary (de)serialization error",
+            #[cfg(feature = "serde")]
+            Error::Json(_) => "JSON (de)serialization error"
+        }
+    }
+}
+
+impl Error {
+    /// Converts a `binrw` error at a `load_*` boundary into a typed `Error`.
+    ///
+    /// The parsers express the format's invariants with binrw's `magic`,
+    /// `assert` and `try_map` facilities, which surface as `BadMagic` and
+    /// `Custom` (wrapping one of our own typed errors). Unwrapping them here
+    /// lets callers discriminate the specific corruption mode rather than
+    /// matching an opaque `Error::BinRw`.
+    pub(crate) fn from_binrw (e: binrw::Error) -> Error {
+        match e {
+            binrw::Error::BadMagic { .. } => Error::InvalidMagic,
+            binrw::Error::Custom { pos, err } => match err.downcast::<Error>() {
+                Ok(typed) => *typed,
+                Err(err) => Error::BinRw(binrw::Error::Custom { pos: pos, err: err })
+            },
+            other => Error::BinRw(other)
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from (e: io::Error) -> Error { Error::Io(e) }
+}
+
+impl From<binrw::Error> for Error {
+    fn from (e: binrw::Error) -> Error { Error::BinRw(e) }
+}
+
+#[cfg(feature = "serde")]
+impl From<serde_json::Error> for Error {
+    fn from (e: serde_json::Error) -> Error { Error::Json(e) }
+}
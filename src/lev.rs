@@ -1,9 +1,11 @@
 //! Read and write Elasto Mania level files.
 
-use std::io::{ Read, Write };
 use std::fs::File;
-use byteorder::{ ByteOrder, ReadBytesExt, WriteBytesExt, LittleEndian };
-use super::{ Position };
+use binrw::{ binrw, BinRead, BinWrite, BinResult };
+use byteorder::{ ByteOrder, LittleEndian };
+use rand::Rng;
+use super::Position;
+use super::Error;
 
 // Magic arbitrary number; signifies end-of-data. Followed by Top10 list(s).
 const EOD: i32 = 0x0067103A;
@@ -11,6 +13,7 @@ const EOD: i32 = 0x0067103A;
 const EOF: i32 = 0x00845D52;
 
 /// Game version.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, PartialEq)]
 pub enum Version {
     Across,
@@ -18,6 +21,7 @@ pub enum Version {
 }
 
 /// Type of object.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, PartialEq)]
 pub enum ObjectType {
     Apple,
@@ -27,11 +31,18 @@ pub enum ObjectType {
 }
 
 /// Object struct. Every level requires one `ObjectType::Player` Object and at least one `ObjectType::Exit` Object.
+#[binrw]
+#[brw(little)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, PartialEq)]
 pub struct Object {
     /// Position. See `Position` struct.
+    #[br(map = |p: (f64, f64)| Position { x: p.0, y: p.1 })]
+    #[bw(map = |p: &Position<f64>| (p.x, p.y))]
     pub position: Position<f64>,
     /// Type of Object, see `ObjectType`.
+    #[br(try_map = object_type_from_i32)]
+    #[bw(map = |o: &ObjectType| object_type_to_i32(o))]
     pub object_type: ObjectType,
     /// Applies to `ObjectType::Apple` only.
     ///
@@ -43,15 +54,46 @@ pub struct Object {
     // TODO: enum with gravity
     pub gravity: i32,
     /// Applies to `ObjectType::Apple` only. Valid values are 1 to 9.
+    #[br(map = |a: i32| a + 1)]
+    #[bw(map = |a: &i32| a - 1)]
     pub animation: i32
 }
 
+fn object_type_from_i32 (value: i32) -> Result<ObjectType, Error> {
+    match value {
+        1 => Ok(ObjectType::Exit),
+        2 => Ok(ObjectType::Apple),
+        3 => Ok(ObjectType::Killer),
+        4 => Ok(ObjectType::Player),
+        t => Err(Error::InvalidObjectType(t))
+    }
+}
+
+fn object_type_to_i32 (object_type: &ObjectType) -> i32 {
+    match *object_type {
+        ObjectType::Exit => 1,
+        ObjectType::Apple => 2,
+        ObjectType::Killer => 3,
+        ObjectType::Player => 4
+    }
+}
+
 /// Polygon struct.
+#[binrw]
+#[brw(little)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Default, PartialEq)]
 pub struct Polygon {
     /// Grass polygon.
+    #[br(map = |g: i32| g > 0)]
+    #[bw(map = |g: &bool| if *g { 1i32 } else { 0i32 })]
     pub grass: bool,
+    #[br(temp)]
+    #[bw(calc = self.vertices.len() as i32)]
+    vertex_count: i32,
     /// Vector with all vertices, see Position struct.
+    #[br(count = vertex_count, map = |v: Vec<(f64, f64)>| v.into_iter().map(|p| Position { x: p.0, y: p.1 }).collect())]
+    #[bw(map = |v: &Vec<Position<f64>>| v.iter().map(|p| (p.x, p.y)).collect::<Vec<(f64, f64)>>())]
     pub vertices: Vec<Position<f64>>
 }
 
@@ -65,14 +107,25 @@ impl Polygon {
 }
 
 /// Picture struct.
+#[binrw]
+#[brw(little)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Picture {
     /// Picture name.
+    #[br(count = 10, map = trim_cstring_lossy)]
+    #[bw(map = |s: &String| pad_string(s, 10))]
     pub name: String,
     /// Texture name.
+    #[br(count = 10, map = trim_cstring_lossy)]
+    #[bw(map = |s: &String| pad_string(s, 10))]
     pub texture: String,
     /// Mask name.
+    #[br(count = 10, map = trim_cstring_lossy)]
+    #[bw(map = |s: &String| pad_string(s, 10))]
     pub mask: String,
     /// Position. See Position struct.
+    #[br(map = |p: (f64, f64)| Position { x: p.0, y: p.1 })]
+    #[bw(map = |p: &Position<f64>| (p.x, p.y))]
     pub position: Position<f64>,
     /// Z-distance
     pub distance: i32,
@@ -82,10 +135,12 @@ pub struct Picture {
     /// 1 = ground
     /// 2 = sky
     // TODO: make enum
+    #[br(assert((0..=2).contains(&clip), Error::InvalidClipValue(clip)))]
     pub clip: i32
 }
 
 /// Top10 list entry struct.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug)]
 pub struct ListEntry {
     /// Player 1 name.
@@ -96,34 +151,108 @@ pub struct ListEntry {
     pub time: i32
 }
 
+/// Axis-aligned bounding box over a level's geometry, mirroring the `Aabb3`
+/// volumes collision and rendering crates expose. Useful for framing or
+/// centering a level in an editor.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundingBox {
+    /// Minimum (top-left) corner.
+    pub min: Position<f64>,
+    /// Maximum (bottom-right) corner.
+    pub max: Position<f64>
+}
+
+/// A problem that makes a level unplayable or invalid. Returned by
+/// `Level::validate`.
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+    /// No `ObjectType::Player` object present.
+    MissingPlayer,
+    /// No `ObjectType::Exit` object present.
+    MissingExit,
+    /// More than one `ObjectType::Player` object present.
+    TooManyPlayers(usize),
+    /// Polygon (by index) with fewer than three vertices.
+    InvalidPolygon(usize),
+    /// Apple object (by index) with an `animation` outside 1..=9.
+    InvalidAppleAnimation(usize),
+    /// Apple object (by index) with a `gravity` outside 0..=4.
+    InvalidAppleGravity(usize)
+}
+
 /// Level struct that contains all level information.
+#[binrw]
+#[brw(little, magic = b"POT14")]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Level {
     /// Elma or Across level.
+    #[br(calc = Version::Elma)]
+    #[bw(ignore)]
     pub version: Version,
-    /// Raw binary data of a loaded or finalized constructed level.
-    raw: Vec<u8>,
     /// Random number that links level file to replay files.
+    #[brw(pad_before = 2)]
     pub link: i32,
     /// Contains four integrity checks (See create_integrity()).
     pub integrity: [f64; 4],
     /// Level name.
+    #[br(count = 51, map = trim_cstring_lossy)]
+    #[bw(map = |s: &String| pad_string(s, 51))]
     pub name: String,
     /// LGR file name.
+    #[br(count = 16, map = trim_cstring_lossy)]
+    #[bw(map = |s: &String| pad_string(s, 16))]
     pub lgr: String,
     /// Ground texture name.
+    #[br(count = 10, map = trim_cstring_lossy)]
+    #[bw(map = |s: &String| pad_string(s, 10))]
     pub ground: String,
     /// Sky texture name.
+    #[br(count = 10, map = trim_cstring_lossy)]
+    #[bw(map = |s: &String| pad_string(s, 10))]
     pub sky: String,
+
+    #[br(temp)]
+    #[bw(calc = self.polygons.len() as f64 + 0.4643643)]
+    polygon_count: f64,
     /// Vector with all polygons (See Polygon).
+    #[br(count = (polygon_count - 0.4643643).round() as usize)]
     pub polygons: Vec<Polygon>,
+
+    #[br(temp)]
+    #[bw(calc = self.objects.len() as f64 + 0.4643643)]
+    object_count: f64,
     /// Vector with all objects (See Object).
+    #[br(count = (object_count - 0.4643643).round() as usize)]
     pub objects: Vec<Object>,
+
+    #[br(temp)]
+    #[bw(calc = self.pictures.len() as f64 + 0.2345672)]
+    picture_count: f64,
     /// Vector with all pictures (See Picture).
+    #[br(count = (picture_count - 0.2345672).round() as usize)]
     pub pictures: Vec<Picture>,
+
+    #[br(temp, assert(eod == EOD, Error::EodMismatch))]
+    #[bw(calc = EOD)]
+    eod: i32,
+
+    // The two top10 lists share a single XOR-encrypted 688-byte block.
+    #[br(temp, count = 688, map = crypt_top10)]
+    #[bw(ignore)]
+    top10_decrypted: Vec<u8>,
     /// Vector of Top10 single-player names and times.
+    #[br(try_calc = parse_top10(&top10_decrypted[0..344]))]
+    #[bw(write_with = write_top10_block, args(&self.top10_multi))]
     pub top10_single: Vec<ListEntry>,
     /// Vector of Top10 multi-player names and times.
-    pub top10_multi: Vec<ListEntry>
+    #[br(try_calc = parse_top10(&top10_decrypted[344..688]))]
+    #[bw(ignore)]
+    pub top10_multi: Vec<ListEntry>,
+
+    #[br(temp, assert(eof == EOF, Error::EofMismatch))]
+    #[bw(calc = EOF)]
+    eof: i32
 }
 
 impl Default for Level {
@@ -141,7 +270,6 @@ impl Level {
     pub fn new () -> Level {
         Level {
             version: Version::Elma,
-            raw: vec![],
             link: 0,
             integrity: [0.0f64; 4],
             name: String::from(""),
@@ -161,164 +289,206 @@ impl Level {
     /// # Examples
     ///
     /// ```
-    /// let level = elma::lev::Level::load_level("tests/test.lev");
+    /// let level = elma::lev::Level::load_level("tests/test.lev").unwrap();
     /// ```
-    pub fn load_level (filename: &str) -> Level {
-        let mut level = Level::new();
-        let mut file = File::open(filename).unwrap();
-        let mut buffer = vec![];
-        file.read_to_end(&mut buffer).unwrap();
-        level.raw = buffer;
-        level.parse_level();
-        level
+    pub fn load_level (filename: &str) -> Result<Level, Error> {
+        let mut file = File::open(filename)?;
+        Level::read(&mut file).map_err(Error::from_binrw)
     }
 
-    /// Parses the raw binary data into Level struct fields.
-    fn parse_level (&mut self) {
-        let mut rem = self.raw.as_slice();
-
-        // Elma = POT14, Across = POT06.
-        // TODO: make Across compatible in 2025.
-        let (version, rem) = rem.split_at(5);
-        self.version = match version {
-            [80, 79, 84, 49, 52] => Version::Elma,
-            [80, 79, 84, 48, 54] => Version::Across,
-            _ => panic!("Not a valid level file.")
-        };
-
-        // Link.
-        let (_, rem) = rem.split_at(2); // Never used
-        self.link = rem.read_i32::<LittleEndian>().unwrap();
-
-        // Integrity checksums.
-        for i in 0..4 {
-            self.integrity[i] = rem.read_f64::<LittleEndian>().unwrap();
+    /// Recalculates the integrity checksums before serialization.
+    ///
+    /// The deterministic sum `integrity[0]` is always recomputed from the
+    /// current geometry, so an edited level still passes the tamper check. A
+    /// level that carries pseudo-random offsets from disk keeps them (shifted
+    /// onto the new sum) so re-saving a level with unchanged geometry writes
+    /// the same four checksums back; a freshly constructed level, whose checksums are still zeroed, has new
+    /// offsets generated here.
+    fn update (&mut self) {
+        if self.integrity == [0.0f64; 4] {
+            self.integrity = self.create_integrity();
+        } else {
+            // Preserve the original offsets relative to the old sum, but rebase
+            // them onto the sum of the (possibly edited) geometry.
+            let s = self.integrity_sum();
+            self.integrity = [
+                s,
+                s + (self.integrity[1] - self.integrity[0]),
+                s + (self.integrity[2] - self.integrity[0]),
+                s + (self.integrity[3] - self.integrity[0])
+            ];
         }
+    }
 
-        // Level name.
-        let (name, rem) = rem.split_at(51);
-        for name_trimmed in name.splitn(1, |c| c == 0) {
-            self.name = String::from_utf8(name_trimmed.to_vec()).unwrap();
-        }
-        // LGR name.
-        let (lgr, rem) = rem.split_at(16);
-        for lgr_trimmed in lgr.splitn(1, |c| c == 0) {
-            self.lgr = String::from_utf8(lgr_trimmed.to_vec()).unwrap();
+    /// Deterministic sum `s` over every polygon vertex and object position,
+    /// plus a per-object type code. This is `integrity[0]`.
+    fn integrity_sum (&self) -> f64 {
+        let mut s = 0f64;
+        for polygon in &self.polygons {
+            for vertex in &polygon.vertices {
+                s += vertex.x + vertex.y;
+            }
         }
-        // Ground texture name.
-        let (ground, rem) = rem.split_at(10);
-        for ground_trimmed in ground.splitn(1, |c| c == 0) {
-            self.ground = String::from_utf8(ground_trimmed.to_vec()).unwrap();
+        for object in &self.objects {
+            s += object.position.x + object.position.y;
+            s += object_type_to_i32(&object.object_type) as f64;
         }
-        // Sky texture name.
-        let (sky, rem) = rem.split_at(10);
-        for sky_trimmed in sky.splitn(1, |c| c == 0) {
-            self.sky = String::from_utf8(sky_trimmed.to_vec()).unwrap();
+        s
+    }
+
+    /// Calculates the four integrity checksums the game uses to detect a
+    /// tampered level file.
+    ///
+    /// `integrity[0]` is the deterministic sum `s` (see `integrity_sum`); the
+    /// remaining three are `s` offset by pseudo-random values inside the ranges
+    /// the game accepts, so an honestly saved level still validates.
+    fn create_integrity (&self) -> [f64; 4] {
+        let s = self.integrity_sum();
+        let mut rng = rand::thread_rng();
+        [
+            s,
+            s + 11877.0 + (rng.gen_range(0..5871) as f64),
+            s + 12112.0 + (rng.gen_range(0..6102) as f64),
+            s + 12112.0 + (rng.gen_range(0..6102) as f64)
+        ]
+    }
+
+    /// Converts all struct fields into raw binary form and returns it.
+    pub fn get_raw (mut self) -> Result<Vec<u8>, Error> {
+        self.update();
+        let mut buffer = std::io::Cursor::new(vec![]);
+        self.write(&mut buffer)?;
+        Ok(buffer.into_inner())
+    }
+
+    /// Saves level as a file.
+    pub fn save_lev (mut self, filename: &str) -> Result<(), Error> {
+        self.update();
+        let mut file = File::create(&filename)?;
+        self.write(&mut file)?;
+        Ok(())
+    }
+
+    /// Computes the axis-aligned bounding box over every polygon vertex and
+    /// object position. Returns a zero-sized box at the origin for an empty
+    /// level.
+    pub fn bounding_box (&self) -> BoundingBox {
+        let points = self.polygons.iter()
+            .flat_map(|p| p.vertices.iter().map(|v| (v.x, v.y)))
+            .chain(self.objects.iter().map(|o| (o.position.x, o.position.y)));
+
+        let mut min = Position { x: f64::MAX, y: f64::MAX };
+        let mut max = Position { x: f64::MIN, y: f64::MIN };
+        let mut seen = false;
+        for (x, y) in points {
+            seen = true;
+            if x < min.x { min.x = x; }
+            if y < min.y { min.y = y; }
+            if x > max.x { max.x = x; }
+            if y > max.y { max.y = y; }
         }
 
-        // Polygons.
-        let poly_count = (buffer.read_f64::<LittleEndian>().unwrap() - 0.4643643).round() as u16;
-        for _ in 0..poly_count {
-            let grass = buffer.read_i32::<LittleEndian>().unwrap() > 0;
-            let vertex_count = buffer.read_i32::<LittleEndian>().unwrap();
-            let mut vertices: Vec<Position<f64>> = vec![];
-            for _ in 0..vertex_count {
-                let x = buffer.read_f64::<LittleEndian>().unwrap();
-                let y = buffer.read_f64::<LittleEndian>().unwrap();
-                vertices.push(Position {
-                    x: x,
-                    y: y
-                });
+        if seen {
+            BoundingBox { min: min, max: max }
+        } else {
+            BoundingBox {
+                min: Position { x: 0.0, y: 0.0 },
+                max: Position { x: 0.0, y: 0.0 }
             }
-            self.polygons.push(Polygon {
-                grass: grass,
-                vertices: vertices
-            });
         }
+    }
 
-        // Objects.
-        let object_count = (buffer.read_f64::<LittleEndian>().unwrap() - 0.4643643).round() as u16;
-        for _ in 0..object_count {
-            let x = buffer.read_f64::<LittleEndian>().unwrap();
-            let y = buffer.read_f64::<LittleEndian>().unwrap();
-            let position = Position { x: x, y: y };
-            let object_type = match buffer.read_i32::<LittleEndian>().unwrap() {
-                1 => ObjectType::Exit,
-                2 => ObjectType::Apple,
-                3 => ObjectType::Killer,
-                4 => ObjectType::Player,
-                _ => panic!("Not a valid object type")
-            };
-            let gravity = buffer.read_i32::<LittleEndian>().unwrap();
-            let animation = buffer.read_i32::<LittleEndian>().unwrap() + 1;
-
-            self.objects.push(Object {
-                position: position,
-                object_type: object_type,
-                gravity: gravity,
-                animation: animation
-            });
-        }
+    /// Returns the number of apple objects in the level.
+    pub fn apple_count (&self) -> usize {
+        self.objects.iter().filter(|o| o.object_type == ObjectType::Apple).count()
+    }
 
-        // Pictures.
-        let picture_count = (buffer.read_f64::<LittleEndian>().unwrap() - 0.2345672).round() as u16;
-        for _ in 0..picture_count {
-            let name = cstring_read(read_n(&mut buffer, 10));
-            let texture = cstring_read(read_n(&mut buffer, 10));
-            let mask = cstring_read(read_n(&mut buffer, 10));
-            let x = buffer.read_f64::<LittleEndian>().unwrap();
-            let y = buffer.read_f64::<LittleEndian>().unwrap();
-            let distance = buffer.read_i32::<LittleEndian>().unwrap();
-            let clip = buffer.read_i32::<LittleEndian>().unwrap();
-
-            self.pictures.push(Picture {
-                name: name,
-                texture: texture,
-                mask: mask,
-                position: Position { x: x, y: y },
-                distance: distance,
-                clip: clip
-            });
-        }
+    /// Returns the number of killer objects in the level.
+    pub fn killer_count (&self) -> usize {
+        self.objects.iter().filter(|o| o.object_type == ObjectType::Killer).count()
+    }
 
-        // EOD marker expected at this point.
-        let expected = buffer.read_i32::<LittleEndian>().unwrap();
-        if expected != EOD { panic!("EOD marker mismatch: x0{:x} != x0{:x}", expected, EOD); }
+    /// Checks the level for problems that would make it unplayable or invalid,
+    /// returning one `Problem` per issue found. An empty vector means the level
+    /// is playable.
+    pub fn validate (&self) -> Vec<Problem> {
+        let mut problems = vec![];
+
+        let players = self.objects.iter().filter(|o| o.object_type == ObjectType::Player).count();
+        if players == 0 {
+            problems.push(Problem::MissingPlayer);
+        } else if players > 1 {
+            problems.push(Problem::TooManyPlayers(players));
+        }
 
-        // First decrypt the top10 blocks.
-        let decrypted_top10_data = crypt_top10(read_n(&mut buffer, 688));
+        if self.objects.iter().all(|o| o.object_type != ObjectType::Exit) {
+            problems.push(Problem::MissingExit);
+        }
 
-        // Single-player list.
-        let single = &decrypted_top10_data[0..344];
-        self.top10_single = parse_top10(single);
+        for (i, polygon) in self.polygons.iter().enumerate() {
+            if polygon.vertices.len() < 3 {
+                problems.push(Problem::InvalidPolygon(i));
+            }
+        }
 
-        // Multi-player list.
-        let multi = &decrypted_top10_data[344..688];
-        self.top10_multi = parse_top10(multi);
+        for (i, object) in self.objects.iter().enumerate() {
+            if object.object_type == ObjectType::Apple {
+                if !(1..=9).contains(&object.animation) {
+                    problems.push(Problem::InvalidAppleAnimation(i));
+                }
+                if !(0..=4).contains(&object.gravity) {
+                    problems.push(Problem::InvalidAppleGravity(i));
+                }
+            }
+        }
 
-        // EOF marker expected at this point.
-        let expected = buffer.read_i32::<LittleEndian>().unwrap();
-        if expected != EOF { panic!("EOF marker mismatch: x0{:x} != x0{:x}", expected, EOF); }
+        problems
     }
 
-    /// Combines the Level struct fields to generate the raw binary data,
-    /// and calculate integrity sums.
-    fn update (&self) {
-        // TODO: convert
+    /// Serializes the level to a human-editable JSON string.
+    ///
+    /// All meaningful level state (version, link, integrity, names, geometry
+    /// and both top10 lists) survives a `to_json`/`from_json`/`save_lev`
+    /// round-trip. Note it is not *byte*-for-byte lossless: the two reserved
+    /// bytes after the magic are normalized to zero, fixed-width name fields
+    /// are re-padded with zeros, and each top10 name is truncated to its
+    /// 14-byte field, so any residual non-zero bytes in those regions are not
+    /// retained.
+    #[cfg(feature = "serde")]
+    pub fn to_json (&self) -> Result<String, Error> {
+        Ok(serde_json::to_string(self)?)
     }
 
-    /// Converts all struct fields into raw binary form and returns it.
-    pub fn get_raw (self) -> Vec<u8> {
-        self.update();
-        self.raw
+    /// Deserializes a level from a JSON string. See `to_json` for the exact
+    /// round-trip guarantees.
+    #[cfg(feature = "serde")]
+    pub fn from_json (json: &str) -> Result<Level, Error> {
+        Ok(serde_json::from_str(json)?)
     }
+}
 
-    /// Saves level as a file.
-    pub fn save_lev (self, filename: &str) {
-        self.update();
-        let mut file = File::create(&filename).unwrap();
-        // TODO: write stuff.
-    }
+/// Reads a fixed-width, null-terminated byte field into an owned `String`,
+/// replacing any invalid UTF-8 so a single bad byte does not abort parsing.
+fn trim_cstring_lossy (field: Vec<u8>) -> String {
+    let end = field.iter().position(|&c| c == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// Encodes `string` into a fixed `width`-byte, null-padded field.
+fn pad_string (string: &str, width: usize) -> Vec<u8> {
+    let mut bytes = string.as_bytes().to_vec();
+    bytes.truncate(width);
+    bytes.resize(width, 0);
+    bytes
+}
+
+/// Re-encrypts and writes both top10 lists as a single 688-byte block.
+#[binrw::writer(writer, endian)]
+fn write_top10_block (single: &[ListEntry], multi: &[ListEntry]) -> BinResult<()> {
+    let mut block = vec![];
+    block.extend_from_slice(&write_top10(single));
+    block.extend_from_slice(&write_top10(multi));
+    crypt_top10(block).write_options(writer, endian, ())
 }
 
 /// Decrypt and encrypt top10 list data. Same algorithm for both.
@@ -327,7 +497,7 @@ pub fn crypt_top10 (mut top10: Vec<u8>) -> Vec<u8> {
     let mut ebp8: i16 = 0x15;
     let mut ebp10: i16 = 0x2637;
 
-    for mut t in top10.iter_mut().take(688) {
+    for t in top10.iter_mut().take(688) {
         *t ^= (ebp8 & 0xFF) as u8;
         ebp10 = ebp10.wrapping_add((ebp8.wrapping_rem(0xD3D)).wrapping_mul(0xD3D));
         ebp8 = ebp10.wrapping_mul(0x1F).wrapping_add(0xD3D);
@@ -336,10 +506,16 @@ pub fn crypt_top10 (mut top10: Vec<u8>) -> Vec<u8> {
     top10
 }
 
-/// Parse top10 lists and return a vector of `ListEntry`s
-pub fn parse_top10 (top10: &[u8]) -> Vec<ListEntry> {
+/// Parse top10 lists and return a vector of `ListEntry`s.
+///
+/// A top10 block holds at most ten entries; a corrupt or forged `times` count
+/// is clamped to that range so a malformed file is handled gracefully instead
+/// of indexing past the block.
+pub fn parse_top10 (top10: &[u8]) -> Result<Vec<ListEntry>, Error> {
+    if top10.len() < 344 { return Err(Error::EodMismatch); }
+
     let mut list: Vec<ListEntry> = vec![];
-    let times = LittleEndian::read_i32(&top10[0..4]);
+    let times = LittleEndian::read_i32(&top10[0..4]).max(0).min(10);
     for n in 0..times {
         let time_offset: usize = (4 + n * 4) as usize;
         let time_end: usize = time_offset + 4;
@@ -354,9 +530,29 @@ pub fn parse_top10 (top10: &[u8]) -> Vec<ListEntry> {
         name2.extend_from_slice(&top10[name_2_offset..name_2_end]);
         list.push(ListEntry {
             time: LittleEndian::read_i32(&top10[time_offset..time_end]),
-            name_1: cstring_read(name),
-            name_2: cstring_read(name2)
+            name_1: trim_cstring_lossy(name),
+            name_2: trim_cstring_lossy(name2)
         });
     }
-    list
+    Ok(list)
+}
+
+/// Builds a single 344-byte top10 list block from a slice of `ListEntry`s.
+fn write_top10 (list: &[ListEntry]) -> Vec<u8> {
+    let mut buffer = vec![0u8; 344];
+    let times = list.len().min(10);
+    LittleEndian::write_i32(&mut buffer[0..4], times as i32);
+    for (n, entry) in list.iter().take(10).enumerate() {
+        let time_offset = 4 + n * 4;
+        LittleEndian::write_i32(&mut buffer[time_offset..time_offset + 4], entry.time);
+        let name_offset = 44 + n * 15;
+        let name = entry.name_1.as_bytes();
+        let len = name.len().min(14);
+        buffer[name_offset..name_offset + len].copy_from_slice(&name[..len]);
+        let name_2_offset = 194 + n * 15;
+        let name_2 = entry.name_2.as_bytes();
+        let len_2 = name_2.len().min(14);
+        buffer[name_2_offset..name_2_offset + len_2].copy_from_slice(&name_2[..len_2]);
+    }
+    buffer
 }
@@ -0,0 +1,35 @@
+//! Library for reading and writing Elasto Mania level and replay files.
+
+extern crate byteorder;
+extern crate binrw;
+extern crate rand;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+
+pub mod error;
+pub mod lev;
+pub mod rec;
+
+pub use error::Error;
+
+/// Shared coordinate pair used throughout levels and replays.
+///
+/// Serializes as a plain `{ "x": .., "y": .. }` object so JSON stays
+/// human-editable.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Position<T> {
+    /// X coordinate.
+    pub x: T,
+    /// Y coordinate.
+    pub y: T
+}
+
+/// Trims a fixed-width, null-terminated byte field into an owned `String`,
+/// replacing any invalid UTF-8 so a single bad byte does not abort parsing.
+pub fn trim_string (field: &[u8]) -> String {
+    let end = field.iter().position(|&c| c == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
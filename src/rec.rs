@@ -1,13 +1,16 @@
 //! Read and write Elasto Mania replay files.
-use std::io::{ Read, Write };
 use std::fs::File;
-use byteorder::{ ReadBytesExt, WriteBytesExt, LittleEndian };
+use binrw::{ binread, BinRead };
 use super::{ Position, trim_string };
+use super::Error;
 
 // Magic arbitrary number to signify end of replay file.
 const EOR: u32 = 0x00492F75;
+// Fixed width of the level-filename field in the replay header.
+const LEVEL_NAME_LENGTH: usize = 12;
 
 /// One frame of replay.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, PartialEq)]
 pub struct Frame {
     /// Bike position?
@@ -32,6 +35,7 @@ pub struct Frame {
     pub volume: i16
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, PartialEq)]
 pub struct Event {
     /// Time of event.
@@ -40,7 +44,94 @@ pub struct Event {
     pub event_type: [u32; 2]
 }
 
+/// On-disk event layout: an `f64` time, two packed `i16` info fields, and a
+/// trailing (currently unused) `f32`.
+#[binread]
+#[br(little)]
+struct RawEvent {
+    time: f64,
+    info_1: i16,
+    info_2: i16,
+    _trailing: f32
+}
+
+/// A single rider block in struct-of-arrays (column) layout, terminated by the
+/// `EOR` marker. Each column holds `count` values before the next begins.
+#[binread]
+#[br(little, import { count: usize })]
+struct RiderBlock {
+    #[br(count = count)] bike_x: Vec<f32>,
+    #[br(count = count)] bike_y: Vec<f32>,
+    #[br(count = count)] left_x: Vec<i16>,
+    #[br(count = count)] left_y: Vec<i16>,
+    #[br(count = count)] right_x: Vec<i16>,
+    #[br(count = count)] right_y: Vec<i16>,
+    #[br(count = count)] head_x: Vec<i16>,
+    #[br(count = count)] head_y: Vec<i16>,
+    #[br(count = count)] rotation: Vec<i16>,
+    #[br(count = count)] left_rotation: Vec<u8>,
+    #[br(count = count)] right_rotation: Vec<u8>,
+    #[br(count = count)] data: Vec<u8>,
+    // Back-wheel speed is not currently exposed, but still has to be consumed.
+    #[br(count = count)] _back_wheel: Vec<u8>,
+    #[br(count = count)] volume: Vec<i16>,
+    #[br(temp)] event_count: i32,
+    #[br(count = event_count)] events: Vec<RawEvent>,
+    #[br(temp, assert(eor == EOR, Error::EorMismatch))] eor: u32
+}
+
+impl RiderBlock {
+    /// Transposes the columns into per-frame structs and decodes the events.
+    fn into_frames_and_events (self) -> (Vec<Frame>, Vec<Event>) {
+        let mut frames = Vec::with_capacity(self.bike_x.len());
+        for i in 0..self.bike_x.len() {
+            frames.push(Frame {
+                bike: Position { x: self.bike_x[i], y: self.bike_y[i] },
+                left_wheel: Position { x: self.left_x[i], y: self.left_y[i] },
+                right_wheel: Position { x: self.right_x[i], y: self.right_y[i] },
+                head: Position { x: self.head_x[i], y: self.head_y[i] },
+                rotation: self.rotation[i],
+                left_wheel_rotation: self.left_rotation[i],
+                right_wheel_rotation: self.right_rotation[i],
+                throttle: self.data[i] & 0b0000_0001 != 0,
+                right: self.data[i] & 0b0000_0010 != 0,
+                volume: self.volume[i]
+            });
+        }
+        let events = self.events.into_iter().map(|e| Event {
+            time: e.time,
+            event_type: [e.info_1 as u32, e.info_2 as u32]
+        }).collect();
+        (frames, events)
+    }
+}
+
+/// The raw on-disk replay layout. The first rider block always follows the
+/// header; multi-player replays append a second one.
+#[binread]
+#[br(little)]
+struct RawReplay {
+    frame_count: i32,
+    #[br(temp)] _reserved: i32,
+    #[br(map = |b: u8| b > 0)] multi: bool,
+    #[br(map = |b: u8| b > 0)] flag_tag: bool,
+    link: u32,
+    #[br(count = LEVEL_NAME_LENGTH, map = |b: Vec<u8>| trim_string(&b))] level: String,
+    #[br(args { count: frame_count as usize })] first: RiderBlock,
+    #[br(if(multi))]
+    #[br(parse_with = parse_second_block)] second: Option<RiderBlock>
+}
+
+/// Parses the second rider block (its own frame count followed by the block).
+#[binrw::parser(reader, endian)]
+fn parse_second_block () -> binrw::BinResult<Option<RiderBlock>> {
+    let frame_count = i32::read_options(reader, endian, ())?;
+    let block = RiderBlock::read_options(reader, endian, binrw::args! { count: frame_count as usize })?;
+    Ok(Some(block))
+}
+
 /// Rec struct
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Rec {
     /// Number of Frames in replay.
     pub frame_count: i32,
@@ -55,7 +146,11 @@ pub struct Rec {
     /// Vector with Frame structs.
     pub frames: Vec<Frame>,
     /// Events.
-    pub events: Vec<Event>
+    pub events: Vec<Event>,
+    /// Second rider's frames, for multi-player replays.
+    pub frames_2: Vec<Frame>,
+    /// Second rider's events, for multi-player replays.
+    pub events_2: Vec<Event>
 }
 
 impl Rec {
@@ -74,7 +169,9 @@ impl Rec {
             link: 0,
             level: String::new(),
             frames: vec![],
-            events: vec![]
+            events: vec![],
+            frames_2: vec![],
+            events_2: vec![]
         }
     }
 
@@ -83,10 +180,41 @@ impl Rec {
     /// # Examples
     ///
     /// ```
-    /// let rec = elma::rec::Rec::load_replay("tests/test.rec");
+    /// let rec = elma::rec::Rec::load_replay("tests/test.rec").unwrap();
     /// ```
-    pub fn load_replay(_filename: &str) -> Rec {
-        Rec::new()
+    pub fn load_replay(filename: &str) -> Result<Rec, Error> {
+        let mut file = File::open(filename)?;
+        let raw = RawReplay::read(&mut file).map_err(Error::from_binrw)?;
+
+        let (frames, events) = raw.first.into_frames_and_events();
+        let (frames_2, events_2) = match raw.second {
+            Some(block) => block.into_frames_and_events(),
+            None => (vec![], vec![])
+        };
+
+        Ok(Rec {
+            frame_count: raw.frame_count,
+            multi: raw.multi,
+            flag_tag: raw.flag_tag,
+            link: raw.link,
+            level: raw.level,
+            frames: frames,
+            events: events,
+            frames_2: frames_2,
+            events_2: events_2
+        })
+    }
+
+    /// Serializes the replay to a human-editable JSON string.
+    #[cfg(feature = "serde")]
+    pub fn to_json (&self) -> Result<String, Error> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Deserializes a replay from a JSON string.
+    #[cfg(feature = "serde")]
+    pub fn from_json (json: &str) -> Result<Rec, Error> {
+        Ok(serde_json::from_str(json)?)
     }
 }
 
@@ -1,43 +1,268 @@
 extern crate elma;
 #[cfg(test)]
 mod tests {
-    use elma::{ lev, rec };
-    use std::ffi::CString;
+    use elma::{ lev, rec, Position };
+    use elma::lev::{ Version, Object, ObjectType, Polygon, Problem };
+
+    /// Builds a three-vertex polygon at the given corner coordinates.
+    fn triangle (a: (f64, f64), b: (f64, f64), c: (f64, f64)) -> Polygon {
+        Polygon {
+            grass: false,
+            vertices: vec![
+                Position { x: a.0, y: a.1 },
+                Position { x: b.0, y: b.1 },
+                Position { x: c.0, y: c.1 }
+            ]
+        }
+    }
+
+    fn object (x: f64, y: f64, object_type: ObjectType) -> Object {
+        Object {
+            position: Position { x: x, y: y },
+            object_type: object_type,
+            gravity: 0,
+            animation: 1
+        }
+    }
 
     #[test]
     fn test_lev_default_values () {
         let level = lev::Level::new();
-        assert_eq!(level.version, "Elma".to_string());
+        assert_eq!(level.version, Version::Elma);
         assert_eq!(level.link, 0);
         assert_eq!(level.integrity, [0.0_f64; 4]);
-        assert_eq!(level.name, CString::new("").unwrap());
-        assert_eq!(level.lgr, CString::new("default").unwrap());
-        assert_eq!(level.ground, CString::new("ground").unwrap());
-        assert_eq!(level.sky, CString::new("sky").unwrap());
+        assert_eq!(level.name, "");
+        assert_eq!(level.lgr, "default");
+        assert_eq!(level.ground, "ground");
+        assert_eq!(level.sky, "sky");
     }
 
     #[test]
     fn test_lev_load_level () {
-        let level = lev::Level::load_level("tests/test.lev");
-        assert_eq!(level.version, "Elma".to_string());
+        let level = lev::Level::load_level("tests/test.lev").unwrap();
+        assert_eq!(level.version, Version::Elma);
         assert_eq!(level.link, 1524269776);
         assert_eq!(level.integrity, [-1148375.210607791,
                                       1164056.210607791,
                                       1162467.210607791,
                                       1162283.210607791]);
-        assert_eq!(level.name, CString::new("Rust test").unwrap());
-        assert_eq!(level.lgr, CString::new("default").unwrap());
-        assert_eq!(level.ground, CString::new("ground").unwrap());
-        assert_eq!(level.sky, CString::new("sky").unwrap());
+        assert_eq!(level.name, "Rust test");
+        assert_eq!(level.lgr, "default");
+        assert_eq!(level.ground, "ground");
+        assert_eq!(level.sky, "sky");
         assert_eq!(level.polygons.len(), 2);
-        //assert_eq!(level.polygons, vec![lev::Polygon { grass: false, vertices: vec![] }, lev::Polygon { grass: true, vertices: vec![] }]);
         assert_eq!(level.objects.len(), 8);
         assert_eq!(level.pictures.len(), 2);
     }
 
+    #[test]
+    fn test_lev_bounding_box () {
+        let mut level = lev::Level::new();
+        level.polygons.push(triangle((-5.0, 2.0), (3.0, -1.0), (0.0, 4.0)));
+        level.objects.push(object(10.0, -8.0, ObjectType::Player));
+        let bb = level.bounding_box();
+        assert_eq!(bb.min, Position { x: -5.0, y: -8.0 });
+        assert_eq!(bb.max, Position { x: 10.0, y: 4.0 });
+    }
+
+    #[test]
+    fn test_lev_bounding_box_empty () {
+        let bb = lev::Level::new().bounding_box();
+        assert_eq!(bb.min, Position { x: 0.0, y: 0.0 });
+        assert_eq!(bb.max, Position { x: 0.0, y: 0.0 });
+    }
+
+    #[test]
+    fn test_lev_validate () {
+        let mut level = lev::Level::new();
+        // Empty level: no player and no exit.
+        assert!(level.validate().contains(&Problem::MissingPlayer));
+        assert!(level.validate().contains(&Problem::MissingExit));
+
+        // A playable level has no problems.
+        level.polygons.push(triangle((0.0, 0.0), (1.0, 0.0), (0.0, 1.0)));
+        level.objects.push(object(0.0, 0.0, ObjectType::Player));
+        level.objects.push(object(1.0, 1.0, ObjectType::Exit));
+        assert!(level.validate().is_empty());
+
+        // A degenerate polygon, a second player and a bad apple are all flagged.
+        level.polygons.push(Polygon { grass: false, vertices: vec![Position { x: 0.0, y: 0.0 }] });
+        level.objects.push(object(2.0, 2.0, ObjectType::Player));
+        level.objects.push(Object {
+            position: Position { x: 3.0, y: 3.0 },
+            object_type: ObjectType::Apple,
+            gravity: 9,
+            animation: 42
+        });
+        let problems = level.validate();
+        assert!(problems.contains(&Problem::TooManyPlayers(2)));
+        assert!(problems.contains(&Problem::InvalidPolygon(1)));
+        assert!(problems.contains(&Problem::InvalidAppleAnimation(3)));
+        assert!(problems.contains(&Problem::InvalidAppleGravity(3)));
+        assert_eq!(level.apple_count(), 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_lev_json_round_trip () {
+        let mut level = lev::Level::new();
+        level.name = "json test".to_string();
+        level.link = 42;
+        level.integrity = [1.0, 2.0, 3.0, 4.0];
+        level.polygons.push(triangle((-5.0, 2.0), (3.0, -1.0), (0.0, 4.0)));
+        level.objects.push(object(0.0, 0.0, ObjectType::Player));
+        level.objects.push(object(1.0, 1.0, ObjectType::Exit));
+
+        let json = level.to_json().unwrap();
+        let parsed = lev::Level::from_json(&json).unwrap();
+
+        assert_eq!(parsed.name, level.name);
+        assert_eq!(parsed.link, level.link);
+        assert_eq!(parsed.integrity, level.integrity);
+        assert_eq!(parsed.polygons, level.polygons);
+        assert_eq!(parsed.objects, level.objects);
+    }
+
     #[test]
     fn test_rec_default_values () {
         let rec = rec::Rec::new();
-        assert_eq!(true, true);
+        assert_eq!(rec.frame_count, 0);
+        assert_eq!(rec.multi, false);
+        assert!(rec.frames.is_empty());
+    }
+
+    #[test]
+    fn test_lev_integrity_round_trip () {
+        let mut level = lev::Level::new();
+        level.name = "integrity".to_string();
+        level.polygons.push(triangle((1.0, 2.0), (3.0, 4.0), (5.0, 6.0)));
+        level.objects.push(object(10.0, 20.0, ObjectType::Player));
+        level.objects.push(object(0.0, 0.0, ObjectType::Exit));
+
+        // s = (1+2 + 3+4 + 5+6) + (10+20 + 4[player]) + (0+0 + 1[exit]) = 56.
+        let path = std::env::temp_dir().join("elma_integrity_round_trip.lev");
+        level.save_lev(path.to_str().unwrap()).unwrap();
+        let loaded = lev::Level::load_level(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(loaded.name, "integrity");
+        assert_eq!(loaded.integrity[0], 56.0);
+        assert_eq!(loaded.polygons.len(), 1);
+        assert_eq!(loaded.objects.len(), 2);
+        assert_eq!(loaded.objects[0].object_type, ObjectType::Player);
+    }
+
+    #[test]
+    fn test_lev_integrity_preserved_across_reload () {
+        // Write a real level file, then exercise the load -> save -> load path.
+        // The checksums carried from disk (non-zero, with pseudo-random offsets)
+        // must be preserved exactly when the geometry is left untouched.
+        let mut level = lev::Level::new();
+        level.name = "reload".to_string();
+        level.polygons.push(triangle((1.5, -2.5), (3.0, 4.0), (-5.0, 6.5)));
+        level.objects.push(object(10.0, 20.0, ObjectType::Player));
+        level.objects.push(object(-1.0, 2.0, ObjectType::Exit));
+
+        let seed = std::env::temp_dir().join("elma_integrity_reload_seed.lev");
+        level.save_lev(seed.to_str().unwrap()).unwrap();
+
+        let first = lev::Level::load_level(seed.to_str().unwrap()).unwrap();
+        let resave = std::env::temp_dir().join("elma_integrity_reload_resave.lev");
+        let first_integrity = first.integrity;
+        first.save_lev(resave.to_str().unwrap()).unwrap();
+        let second = lev::Level::load_level(resave.to_str().unwrap()).unwrap();
+
+        std::fs::remove_file(&seed).ok();
+        std::fs::remove_file(&resave).ok();
+
+        assert_eq!(second.integrity, first_integrity);
+    }
+
+    /// Appends a single-frame rider block (struct-of-arrays columns, no events)
+    /// terminated by the `EOR` marker.
+    fn push_single_frame_block (buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&1.5f32.to_le_bytes()); // bike x
+        buffer.extend_from_slice(&2.5f32.to_le_bytes()); // bike y
+        buffer.extend_from_slice(&3i16.to_le_bytes()); // left x
+        buffer.extend_from_slice(&4i16.to_le_bytes()); // left y
+        buffer.extend_from_slice(&5i16.to_le_bytes()); // right x
+        buffer.extend_from_slice(&6i16.to_le_bytes()); // right y
+        buffer.extend_from_slice(&7i16.to_le_bytes()); // head x
+        buffer.extend_from_slice(&8i16.to_le_bytes()); // head y
+        buffer.extend_from_slice(&1000i16.to_le_bytes()); // rotation
+        buffer.push(10); // left wheel rotation
+        buffer.push(20); // right wheel rotation
+        buffer.push(0b0000_0011); // data: throttle + right
+        buffer.push(50); // back wheel speed
+        buffer.extend_from_slice(&99i16.to_le_bytes()); // volume
+        buffer.extend_from_slice(&0i32.to_le_bytes()); // event count
+        buffer.extend_from_slice(&0x0049_2F75u32.to_le_bytes()); // EOR
+    }
+
+    fn assert_single_frame (frame: &rec::Frame) {
+        assert_eq!(frame.bike, Position { x: 1.5, y: 2.5 });
+        assert_eq!(frame.left_wheel, Position { x: 3, y: 4 });
+        assert_eq!(frame.right_wheel, Position { x: 5, y: 6 });
+        assert_eq!(frame.head, Position { x: 7, y: 8 });
+        assert_eq!(frame.rotation, 1000);
+        assert_eq!(frame.left_wheel_rotation, 10);
+        assert_eq!(frame.right_wheel_rotation, 20);
+        assert!(frame.throttle);
+        assert!(frame.right);
+        assert_eq!(frame.volume, 99);
+    }
+
+    #[test]
+    fn test_rec_load_replay_single () {
+        let mut buffer = vec![];
+        buffer.extend_from_slice(&1i32.to_le_bytes()); // frame count
+        buffer.extend_from_slice(&0x83i32.to_le_bytes()); // reserved
+        buffer.push(0); // multi
+        buffer.push(0); // flag tag
+        buffer.extend_from_slice(&12345u32.to_le_bytes()); // link
+        let mut level = b"test".to_vec();
+        level.resize(12, 0);
+        buffer.extend_from_slice(&level); // level filename
+        push_single_frame_block(&mut buffer);
+
+        let path = std::env::temp_dir().join("elma_load_replay_single.rec");
+        std::fs::write(&path, &buffer).unwrap();
+        let replay = rec::Rec::load_replay(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(replay.frame_count, 1);
+        assert_eq!(replay.multi, false);
+        assert_eq!(replay.link, 12345);
+        assert_eq!(replay.level, "test");
+        assert_eq!(replay.frames.len(), 1);
+        assert_single_frame(&replay.frames[0]);
+        assert!(replay.frames_2.is_empty());
+    }
+
+    #[test]
+    fn test_rec_load_replay_multi () {
+        let mut buffer = vec![];
+        buffer.extend_from_slice(&1i32.to_le_bytes()); // frame count
+        buffer.extend_from_slice(&0x83i32.to_le_bytes()); // reserved
+        buffer.push(1); // multi
+        buffer.push(0); // flag tag
+        buffer.extend_from_slice(&1u32.to_le_bytes()); // link
+        let mut level = b"multi".to_vec();
+        level.resize(12, 0);
+        buffer.extend_from_slice(&level);
+        push_single_frame_block(&mut buffer); // first rider
+        buffer.extend_from_slice(&1i32.to_le_bytes()); // second rider frame count
+        push_single_frame_block(&mut buffer); // second rider
+
+        let path = std::env::temp_dir().join("elma_load_replay_multi.rec");
+        std::fs::write(&path, &buffer).unwrap();
+        let replay = rec::Rec::load_replay(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert!(replay.multi);
+        assert_eq!(replay.frames.len(), 1);
+        assert_eq!(replay.frames_2.len(), 1);
+        assert_single_frame(&replay.frames[0]);
+        assert_single_frame(&replay.frames_2[0]);
     }
 }